@@ -1,18 +1,27 @@
+pub mod console;
+pub mod elf;
+
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::cell::RefCell;
 use core::mem::MaybeUninit;
+use elf::LoadedElf;
 use mythril_core::allocator::FrameAllocator;
 use mythril_core::error::{Error, Result};
 use mythril_core::memory::{HostPhysAddr, HostPhysFrame};
 use mythril_core::vm::VmServices;
 use uefi::data_types::Handle;
 use uefi::prelude::ResultExt;
-use uefi::proto::media::file::{File, FileAttribute, FileMode, FileType};
+use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode, FileType};
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::table::boot::{AllocateType, BootServices, MemoryType};
 
+const DEFAULT_CACHE_BUDGET_BYTES: usize = 16 * 1024 * 1024;
+
 pub struct EfiVmServices<'a> {
     bt: &'a BootServices,
     alloc: EfiAllocator<'a>,
+    cache: RefCell<FileCache>,
 }
 
 impl<'a> VmServices for EfiVmServices<'a> {
@@ -21,70 +30,277 @@ impl<'a> VmServices for EfiVmServices<'a> {
         &mut self.alloc
     }
     fn read_file(&self, path: &str) -> Result<Vec<u8>> {
-        read_file(self.bt, path)
+        if let Some(contents) = self.cache.borrow_mut().get(path) {
+            return Ok(contents);
+        }
+
+        // Fall back to fw_cfg only when there was no file to find; other
+        // errors are real usage errors and should propagate.
+        let contents = match read_file(self.bt, path) {
+            Ok(contents) => contents,
+            Err(Error::MissingFile(_)) => read_named_blob(path)?,
+            Err(err) => return Err(err),
+        };
+
+        self.cache
+            .borrow_mut()
+            .insert(String::from(path), contents.clone());
+        Ok(contents)
     }
 }
 
 impl<'a> EfiVmServices<'a> {
     pub fn new(bt: &'a BootServices) -> Self {
+        Self::with_cache_budget(bt, DEFAULT_CACHE_BUDGET_BYTES)
+    }
+
+    pub fn with_cache_budget(bt: &'a BootServices, cache_budget_bytes: usize) -> Self {
         Self {
             bt: bt,
             alloc: EfiAllocator::new(bt),
+            cache: RefCell::new(FileCache::new(cache_budget_bytes)),
         }
     }
+
+    // Rescans for CONVENTIONAL memory that appeared since EfiAllocator::new
+    // and hands everything off to a RuntimeAllocator. Call immediately
+    // before ExitBootServices.
+    pub fn into_runtime_allocator(self) -> Result<RuntimeAllocator> {
+        let mut regions = self.alloc.regions;
+        regions.extend(
+            EfiAllocator::scan_memory_map(self.bt)
+                .ok_or_else(|| Error::Uefi("Failed to rescan memory map for handoff".into()))?,
+        );
+
+        Ok(RuntimeAllocator {
+            regions,
+            free_list: self.alloc.free_list,
+        })
+    }
+
+    pub fn load_elf_kernel(&mut self, path: &str) -> Result<LoadedElf> {
+        let bytes = self.read_file(path)?;
+        elf::load(&bytes, &mut self.alloc)
+    }
+
+    pub fn framebuffer_console(&self, font_path: &str) -> Result<console::FbConsole> {
+        let font_bytes = self.read_file(font_path)?;
+        console::FbConsole::new(self.bt, &font_bytes)
+    }
+}
+
+struct FrameRegion {
+    next_frame: u64,
+    frames_left: u64,
 }
 
 pub struct EfiAllocator<'a> {
     bt: &'a BootServices,
+    regions: Vec<FrameRegion>,
+    free_list: Vec<HostPhysFrame>,
 }
 
+// Cap on the single region reserved up front by scan_memory_map, so
+// EfiAllocator leaves the rest of CONVENTIONAL memory for the firmware's
+// pool allocator (which backs every Vec/String allocated through this
+// file) instead of claiming all of it.
+const INITIAL_REGION_PAGES: u64 = 16 * 1024; // 64 MiB
+
 impl<'a> EfiAllocator<'a> {
     pub fn new(bt: &'a BootServices) -> Self {
-        EfiAllocator { bt: bt }
+        EfiAllocator {
+            bt: bt,
+            regions: Self::scan_memory_map(bt).unwrap_or_else(Vec::new),
+            free_list: Vec::new(),
+        }
     }
-}
 
-impl<'a> FrameAllocator for EfiAllocator<'a> {
-    fn allocate_frame(&mut self) -> Result<HostPhysFrame> {
-        let ty = AllocateType::AnyPages;
-        let mem_ty = MemoryType::LOADER_DATA;
+    // Reserves one bounded region out of the largest CONVENTIONAL descriptor
+    // with the firmware, so the bump allocator has a chunk of memory no pool
+    // allocation can land in without claiming all of usable RAM. Returns
+    // None if the map can't be obtained or has no CONVENTIONAL memory.
+    fn scan_memory_map(bt: &BootServices) -> Option<Vec<FrameRegion>> {
+        let map_size = bt.memory_map_size();
+        // The map can grow between the size query and the call below.
+        let mut buf = vec![0u8; map_size.map_size + 2 * map_size.entry_size];
+        let (_key, descriptors) = bt.memory_map(&mut buf).log_warning().ok()?;
+
+        let (phys_start, page_count) = descriptors
+            .filter(|d| d.ty == MemoryType::CONVENTIONAL && d.page_count > 0)
+            .map(|d| (d.phys_start, d.page_count))
+            .max_by_key(|&(_, page_count)| page_count)?;
+        let page_count = page_count.min(INITIAL_REGION_PAGES);
+
+        bt.allocate_pages(
+            AllocateType::Address(phys_start),
+            MemoryType::LOADER_DATA,
+            page_count as usize,
+        )
+        .log_warning()
+        .ok()?;
+
+        Some(vec![FrameRegion {
+            next_frame: phys_start,
+            frames_left: page_count,
+        }])
+    }
+
+    pub fn allocate_frames(
+        &mut self,
+        count: u64,
+        align: u64,
+        zero: bool,
+    ) -> Result<HostPhysFrame> {
+        if let Some(frame) = bump_allocate(&mut self.regions, count, align, zero) {
+            return Ok(frame);
+        }
+
+        // AnyPages gives no alignment beyond one page, so this fallback can
+        // only honor a request with align <= 1, whatever its frame count.
+        if align > 1 {
+            return Err(Error::Uefi(
+                "EfiAllocator has no tracked region left to satisfy an aligned allocation"
+                    .into(),
+            ));
+        }
+
         let pg = self
             .bt
-            .allocate_pages(ty, mem_ty, 1)
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, count as usize)
             .log_warning()
-            .map_err(|_| Error::Uefi("EfiAllocator failed to allocate frame".into()))?;
+            .map_err(|_| Error::Uefi("EfiAllocator failed to allocate frames".into()))?;
 
-        //FIXME: For now, zero every frame we allocate
-        let ptr = pg as *mut u8;
+        if zero {
+            unsafe {
+                core::ptr::write_bytes(pg as *mut u8, 0, (count * 4096) as usize);
+            }
+        }
+        HostPhysFrame::from_start_address(HostPhysAddr::new(pg))
+    }
+}
+
+fn bump_allocate(
+    regions: &mut [FrameRegion],
+    count: u64,
+    align: u64,
+    zero: bool,
+) -> Option<HostPhysFrame> {
+    let align_bytes = align.max(1) * 4096;
+
+    let (region, aligned_start, slack_frames) = regions.iter_mut().find_map(|r| {
+        let aligned_start = align_up(r.next_frame, align_bytes);
+        let slack_frames = (aligned_start - r.next_frame) / 4096;
+        if r.frames_left >= slack_frames + count {
+            Some((r, aligned_start, slack_frames))
+        } else {
+            None
+        }
+    })?;
+
+    region.next_frame = aligned_start + count * 4096;
+    region.frames_left -= slack_frames + count;
+
+    if zero {
         unsafe {
-            core::ptr::write_bytes(ptr, 0, 4096);
+            core::ptr::write_bytes(aligned_start as *mut u8, 0, (count * 4096) as usize);
         }
+    }
+    HostPhysFrame::from_start_address(HostPhysAddr::new(aligned_start)).ok()
+}
 
-        HostPhysFrame::from_start_address(HostPhysAddr::new(pg))
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+// `mythril_core::allocator::FrameAllocator` has no contiguous-run method, so
+// this extends it locally for callers (e.g. the ELF loader) that need one
+// and can't reach the inherent `allocate_frames` on the concrete allocator
+// types through a `&mut impl FrameAllocator` bound.
+pub trait FrameAllocatorExt: FrameAllocator {
+    fn allocate_frames(&mut self, count: u64, align: u64, zero: bool) -> Result<HostPhysFrame>;
+}
+
+impl<'a> FrameAllocatorExt for EfiAllocator<'a> {
+    fn allocate_frames(&mut self, count: u64, align: u64, zero: bool) -> Result<HostPhysFrame> {
+        EfiAllocator::allocate_frames(self, count, align, zero)
+    }
+}
+
+impl FrameAllocatorExt for RuntimeAllocator {
+    fn allocate_frames(&mut self, count: u64, align: u64, zero: bool) -> Result<HostPhysFrame> {
+        RuntimeAllocator::allocate_frames(self, count, align, zero)
+    }
+}
+
+impl<'a> FrameAllocator for EfiAllocator<'a> {
+    fn allocate_frame(&mut self) -> Result<HostPhysFrame> {
+        if let Some(frame) = self.free_list.pop() {
+            unsafe {
+                core::ptr::write_bytes(frame.start_address().as_u64() as *mut u8, 0, 4096);
+            }
+            return Ok(frame);
+        }
+
+        self.allocate_frames(1, 1, true)
     }
 
     fn deallocate_frame(&mut self, frame: HostPhysFrame) -> Result<()> {
-        self.bt
-            .free_pages(frame.start_address().as_u64(), 1)
-            .log_warning()
-            .map_err(|_| Error::Uefi("EfiAllocator failed to deallocate frame".into()))
+        self.free_list.push(frame);
+        Ok(())
+    }
+}
+
+pub struct RuntimeAllocator {
+    regions: Vec<FrameRegion>,
+    free_list: Vec<HostPhysFrame>,
+}
+
+impl RuntimeAllocator {
+    pub fn allocate_frames(
+        &mut self,
+        count: u64,
+        align: u64,
+        zero: bool,
+    ) -> Result<HostPhysFrame> {
+        bump_allocate(&mut self.regions, count, align, zero)
+            .ok_or_else(|| Error::Uefi("RuntimeAllocator ran out of usable memory".into()))
+    }
+}
+
+impl FrameAllocator for RuntimeAllocator {
+    fn allocate_frame(&mut self) -> Result<HostPhysFrame> {
+        if let Some(frame) = self.free_list.pop() {
+            unsafe {
+                core::ptr::write_bytes(frame.start_address().as_u64() as *mut u8, 0, 4096);
+            }
+            return Ok(frame);
+        }
+
+        self.allocate_frames(1, 1, true)
+    }
+
+    fn deallocate_frame(&mut self, frame: HostPhysFrame) -> Result<()> {
+        self.free_list.push(frame);
+        Ok(())
     }
 }
 
 //FIXME this whole function is rough
 fn read_file(services: &BootServices, path: &str) -> Result<Vec<u8>> {
     let fs = uefi::table::boot::SearchType::from_proto::<SimpleFileSystem>();
+    // No filesystem to search is the same failure mode as not finding
+    // `path`, so report both as MissingFile for the fw_cfg fallback.
     let num_handles = services
         .locate_handle(fs, None)
         .log_warning()
-        .map_err(|_| Error::Uefi("Failed to get number of FS handles".into()))?;
+        .map_err(|_| Error::MissingFile(format!("No filesystem available while looking for {}", path)))?;
 
     let mut volumes: Vec<Handle> =
         vec![unsafe { MaybeUninit::uninit().assume_init() }; num_handles];
     let _ = services
         .locate_handle(fs, Some(&mut volumes))
         .log_warning()
-        .map_err(|_| Error::Uefi("Failed to read FS handles".into()))?;
+        .map_err(|_| Error::MissingFile(format!("No filesystem available while looking for {}", path)))?;
 
     for volume in volumes.into_iter() {
         let proto = services
@@ -114,15 +330,32 @@ fn read_file(services: &BootServices, path: &str) -> Result<Vec<u8>> {
         match file {
             FileType::Regular(mut f) => {
                 info!("Reading file: {}", path);
-                let mut contents = vec![];
-                let mut buff = [0u8; 1024];
-                while f
-                    .read(&mut buff)
+
+                // FileInfo is variable-length, so ask-then-retry to size it.
+                let needed = match f.get_info::<FileInfo>(&mut []) {
+                    Ok(_) => 0,
+                    Err(err) => (*err.data()).ok_or_else(|| {
+                        Error::Uefi(format!("Failed to size file info for: {}", path))
+                    })?,
+                };
+                let mut info_buf = vec![0u8; needed];
+                let file_size = f
+                    .get_info::<FileInfo>(&mut info_buf)
                     .log_warning()
-                    .map_err(|_| Error::Uefi(format!("Failed to read file: {}", path)))?
-                    > 0
-                {
-                    contents.extend_from_slice(&buff);
+                    .map_err(|_| Error::Uefi(format!("Failed to get file info for: {}", path)))?
+                    .file_size();
+
+                let mut contents = Vec::with_capacity(file_size as usize);
+                let mut buff = [0u8; 1024];
+                loop {
+                    let bytes_read = f
+                        .read(&mut buff)
+                        .log_warning()
+                        .map_err(|_| Error::Uefi(format!("Failed to read file: {}", path)))?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    contents.extend_from_slice(&buff[..bytes_read]);
                 }
                 return Ok(contents);
             }
@@ -134,4 +367,149 @@ fn read_file(services: &BootServices, path: &str) -> Result<Vec<u8>> {
         "Unable to find image file {}",
         path
     )))
+}
+
+// Minimal driver for QEMU's fw_cfg device, used as a fallback blob source
+// when no filesystem is available or none of them holds the path.
+mod fw_cfg {
+    const SELECTOR_PORT: u16 = 0x510;
+    const DATA_PORT: u16 = 0x511;
+
+    const FW_CFG_SIGNATURE: u16 = 0x0000;
+    const FW_CFG_FILE_DIR: u16 = 0x0019;
+
+    const QEMU_SIGNATURE: [u8; 4] = *b"QEMU";
+
+    unsafe fn outw(port: u16, val: u16) {
+        core::arch::asm!("out dx, ax", in("dx") port, in("ax") val, options(nomem, nostack, preserves_flags));
+    }
+
+    unsafe fn inb(port: u16) -> u8 {
+        let val: u8;
+        core::arch::asm!("in al, dx", out("al") val, in("dx") port, options(nomem, nostack, preserves_flags));
+        val
+    }
+
+    fn select(key: u16) {
+        unsafe { outw(SELECTOR_PORT, key) };
+    }
+
+    fn read_bytes(buf: &mut [u8]) {
+        for b in buf.iter_mut() {
+            *b = unsafe { inb(DATA_PORT) };
+        }
+    }
+
+    fn read_u16() -> u16 {
+        let mut buf = [0u8; 2];
+        read_bytes(&mut buf);
+        u16::from_be_bytes(buf)
+    }
+
+    fn read_u32() -> u32 {
+        let mut buf = [0u8; 4];
+        read_bytes(&mut buf);
+        u32::from_be_bytes(buf)
+    }
+
+    struct FileEntry {
+        size: u32,
+        select: u16,
+        name: [u8; 56],
+    }
+
+    impl FileEntry {
+        fn name_str(&self) -> &str {
+            let len = self.name.iter().position(|&b| b == 0).unwrap_or(56);
+            core::str::from_utf8(&self.name[..len]).unwrap_or("")
+        }
+    }
+
+    fn is_present() -> bool {
+        select(FW_CFG_SIGNATURE);
+        let mut sig = [0u8; 4];
+        read_bytes(&mut sig);
+        sig == QEMU_SIGNATURE
+    }
+
+    fn find_file(name: &str) -> Option<FileEntry> {
+        select(FW_CFG_FILE_DIR);
+        let count = read_u32();
+        for _ in 0..count {
+            let size = read_u32();
+            let select = read_u16();
+            let _reserved = read_u16();
+            let mut entry_name = [0u8; 56];
+            read_bytes(&mut entry_name);
+            let entry = FileEntry {
+                size,
+                select,
+                name: entry_name,
+            };
+            if entry.name_str() == name {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    pub fn read(name: &str) -> Option<alloc::vec::Vec<u8>> {
+        if !is_present() {
+            return None;
+        }
+
+        let entry = find_file(name)?;
+        select(entry.select);
+        let mut contents = alloc::vec![0u8; entry.size as usize];
+        read_bytes(&mut contents);
+        Some(contents)
+    }
+}
+
+fn read_named_blob(name: &str) -> Result<Vec<u8>> {
+    fw_cfg::read(name)
+        .ok_or_else(|| Error::MissingFile(format!("Unable to find fw_cfg blob {}", name)))
+}
+
+struct FileCache {
+    budget_bytes: usize,
+    used_bytes: usize,
+    // Most-recently-used entry is at the front.
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl FileCache {
+    fn new(budget_bytes: usize) -> Self {
+        FileCache {
+            budget_bytes,
+            used_bytes: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, path: &str) -> Option<Vec<u8>> {
+        let pos = self.entries.iter().position(|(p, _)| p == path)?;
+        let entry = self.entries.remove(pos);
+        let contents = entry.1.clone();
+        self.entries.insert(0, entry);
+        Some(contents)
+    }
+
+    fn insert(&mut self, path: String, contents: Vec<u8>) {
+        let size = contents.len();
+        // Don't bother caching something that can never fit.
+        if size > self.budget_bytes {
+            return;
+        }
+
+        while self.used_bytes + size > self.budget_bytes {
+            match self.entries.pop() {
+                Some((_, evicted)) => self.used_bytes -= evicted.len(),
+                None => break,
+            }
+        }
+
+        self.used_bytes += size;
+        self.entries.insert(0, (path, contents));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,262 @@
+use alloc::vec::Vec;
+use mythril_core::error::{Error, Result};
+use uefi::prelude::ResultExt;
+use uefi::proto::console::gop::{GraphicsOutput, PixelFormat};
+use uefi::table::boot::BootServices;
+
+const PSF1_MAGIC: u16 = 0x0436;
+const PSF1_GLYPH_WIDTH: usize = 8;
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+
+fn read_u32(bytes: &[u8], off: usize) -> Result<u32> {
+    bytes
+        .get(off..off + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| Error::Uefi("PSF font header truncated".into()))
+}
+
+struct Font {
+    width: usize,
+    height: usize,
+    bytes_per_glyph: usize,
+    num_glyphs: usize,
+    glyphs: Vec<u8>,
+}
+
+impl Font {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() >= 4 && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == PSF2_MAGIC
+        {
+            return Self::parse_psf2(data);
+        }
+        if data.len() >= 2 && u16::from_le_bytes([data[0], data[1]]) == PSF1_MAGIC {
+            return Self::parse_psf1(data);
+        }
+        Err(Error::Uefi("Unrecognized PSF font magic".into()))
+    }
+
+    fn parse_psf2(data: &[u8]) -> Result<Self> {
+        let headersize = read_u32(data, 8)? as usize;
+        let num_glyphs = read_u32(data, 16)? as usize;
+        let bytes_per_glyph = read_u32(data, 20)? as usize;
+        let height = read_u32(data, 24)? as usize;
+        let width = read_u32(data, 28)? as usize;
+
+        if num_glyphs == 0 || width == 0 || height == 0 || bytes_per_glyph == 0 {
+            return Err(Error::Uefi("PSF2 font header has a zero dimension".into()));
+        }
+        if bytes_per_glyph < height * ((width + 7) / 8) {
+            return Err(Error::Uefi(
+                "PSF2 font header bytesperglyph is too small for its width/height".into(),
+            ));
+        }
+
+        let glyphs = data
+            .get(headersize..headersize + num_glyphs * bytes_per_glyph)
+            .ok_or_else(|| Error::Uefi("PSF2 font glyph table truncated".into()))?
+            .to_vec();
+
+        Ok(Font {
+            width,
+            height,
+            bytes_per_glyph,
+            num_glyphs,
+            glyphs,
+        })
+    }
+
+    fn parse_psf1(data: &[u8]) -> Result<Self> {
+        let mode = *data
+            .get(2)
+            .ok_or_else(|| Error::Uefi("PSF1 font header truncated".into()))?;
+        let charsize = *data
+            .get(3)
+            .ok_or_else(|| Error::Uefi("PSF1 font header truncated".into()))? as usize;
+        let num_glyphs = if mode & 0x01 != 0 { 512 } else { 256 };
+
+        if charsize == 0 {
+            return Err(Error::Uefi("PSF1 font header has a zero glyph size".into()));
+        }
+
+        let glyphs = data
+            .get(4..4 + num_glyphs * charsize)
+            .ok_or_else(|| Error::Uefi("PSF1 font glyph table truncated".into()))?
+            .to_vec();
+
+        Ok(Font {
+            width: PSF1_GLYPH_WIDTH,
+            height: charsize,
+            bytes_per_glyph: charsize,
+            num_glyphs,
+            glyphs,
+        })
+    }
+
+    fn glyph(&self, ch: u8) -> &[u8] {
+        let idx = (ch as usize).min(self.num_glyphs - 1);
+        &self.glyphs[idx * self.bytes_per_glyph..(idx + 1) * self.bytes_per_glyph]
+    }
+
+    fn bytes_per_row(&self) -> usize {
+        (self.width + 7) / 8
+    }
+}
+
+pub struct FbConsole {
+    fb_base: *mut u8,
+    stride: usize,
+    width: usize,
+    height: usize,
+    bgr: bool,
+    font: Font,
+    cols: usize,
+    rows: usize,
+    cursor_col: usize,
+    cursor_row: usize,
+    fg: u32,
+    bg: u32,
+}
+
+const BYTES_PER_PIXEL: usize = 4;
+const DEFAULT_FG: u32 = 0x00ff_ffff;
+const DEFAULT_BG: u32 = 0x0000_0000;
+
+impl FbConsole {
+    pub fn new(bt: &BootServices, font_bytes: &[u8]) -> Result<Self> {
+        let gop = bt
+            .locate_protocol::<GraphicsOutput>()
+            .log_warning()
+            .map_err(|_| Error::Uefi("Failed to locate Graphics Output Protocol".into()))?;
+        let gop = unsafe { gop.get().as_mut() }
+            .ok_or_else(|| Error::NullPtr("GOP protocol ptr was NULL".into()))?;
+
+        let mode_info = gop.current_mode_info();
+        let (width, height) = mode_info.resolution();
+        let stride = mode_info.stride();
+        let bgr = match mode_info.pixel_format() {
+            PixelFormat::Rgb => false,
+            PixelFormat::Bgr => true,
+            _ => {
+                return Err(Error::Uefi(
+                    "Only packed RGB/BGR GOP pixel formats are supported".into(),
+                ))
+            }
+        };
+
+        let fb_base = gop.frame_buffer().as_mut_ptr();
+        let font = Font::parse(font_bytes)?;
+        if font.width > width || font.height > height {
+            return Err(Error::Uefi(
+                "Font glyph size is larger than the active GOP mode".into(),
+            ));
+        }
+        let cols = width / font.width;
+        let rows = height / font.height;
+
+        Ok(FbConsole {
+            fb_base,
+            stride,
+            width,
+            height,
+            bgr,
+            font,
+            cols,
+            rows,
+            cursor_col: 0,
+            cursor_row: 0,
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+        })
+    }
+
+    fn pack(&self, color: u32) -> u32 {
+        if self.bgr {
+            let r = (color >> 16) & 0xff;
+            let g = (color >> 8) & 0xff;
+            let b = color & 0xff;
+            (b << 16) | (g << 8) | r
+        } else {
+            color
+        }
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u32) {
+        let offset = (y * self.stride + x) * BYTES_PER_PIXEL;
+        unsafe {
+            let ptr = self.fb_base.add(offset) as *mut u32;
+            ptr.write_volatile(self.pack(color));
+        }
+    }
+
+    fn blit_glyph(&mut self, ch: u8) {
+        let bytes_per_row = self.font.bytes_per_row();
+        let glyph = self.font.glyph(ch).to_vec();
+        let (font_w, font_h) = (self.font.width, self.font.height);
+        let (fg, bg) = (self.fg, self.bg);
+
+        let origin_x = self.cursor_col * font_w;
+        let origin_y = self.cursor_row * font_h;
+
+        for row in 0..font_h {
+            for col in 0..font_w {
+                let byte = glyph[row * bytes_per_row + col / 8];
+                let set = (byte >> (7 - (col % 8))) & 1 != 0;
+                self.put_pixel(origin_x + col, origin_y + row, if set { fg } else { bg });
+            }
+        }
+    }
+
+    fn scroll(&mut self) {
+        let row_bytes = self.font.height * self.stride * BYTES_PER_PIXEL;
+        let screen_bytes = self.height * self.stride * BYTES_PER_PIXEL;
+        unsafe {
+            core::ptr::copy(
+                self.fb_base.add(row_bytes),
+                self.fb_base,
+                screen_bytes - row_bytes,
+            );
+            core::ptr::write_bytes(self.fb_base.add(screen_bytes - row_bytes), 0, row_bytes);
+        }
+    }
+
+    fn newline(&mut self) {
+        self.cursor_col = 0;
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    pub fn put_char(&mut self, ch: char) {
+        if ch == '\n' {
+            self.newline();
+            return;
+        }
+
+        if self.cursor_col >= self.cols {
+            self.newline();
+        }
+
+        // The PSF glyph table is indexed by raw byte value; code points past
+        // 0xff are clamped to 0xff, and anything past the font's glyph count
+        // falls back to whatever glyph `Font::glyph` clamps to.
+        let byte = if (ch as u32) <= 0xff { ch as u8 } else { 0xff };
+        self.blit_glyph(byte);
+        self.cursor_col += 1;
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        for ch in s.chars() {
+            self.put_char(ch);
+        }
+    }
+}
+
+impl core::fmt::Write for FbConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        FbConsole::write_str(self, s);
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use alloc::vec::Vec;
+use mythril_core::error::{Error, Result};
+use mythril_core::memory::{HostPhysAddr, HostPhysFrame};
+
+use super::FrameAllocatorExt;
+
+const EI_MAG: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const EI_CLASS: usize = 4;
+const ELFCLASS64: u8 = 2;
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const PAGE_SIZE: u64 = 4096;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+fn read_u16(bytes: &[u8], off: usize) -> Result<u16> {
+    bytes
+        .get(off..off + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| Error::Uefi("ELF header truncated".into()))
+}
+
+fn read_u32(bytes: &[u8], off: usize) -> Result<u32> {
+    bytes
+        .get(off..off + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| Error::Uefi("ELF header truncated".into()))
+}
+
+fn read_u64(bytes: &[u8], off: usize) -> Result<u64> {
+    bytes
+        .get(off..off + 8)
+        .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+        .ok_or_else(|| Error::Uefi("ELF header truncated".into()))
+}
+
+struct Ehdr {
+    e_entry: u64,
+    e_phoff: u64,
+    e_phentsize: u16,
+    e_phnum: u16,
+}
+
+impl Ehdr {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < EHDR_SIZE || bytes[0..4] != EI_MAG {
+            return Err(Error::Uefi("Not an ELF file".into()));
+        }
+        if bytes[EI_CLASS] != ELFCLASS64 {
+            return Err(Error::Uefi("Only ELF64 images are supported".into()));
+        }
+
+        let e_type = read_u16(bytes, 16)?;
+        if e_type != ET_EXEC && e_type != ET_DYN {
+            return Err(Error::Uefi(
+                "ELF image is not an executable or shared object".into(),
+            ));
+        }
+
+        Ok(Ehdr {
+            e_entry: read_u64(bytes, 24)?,
+            e_phoff: read_u64(bytes, 32)?,
+            e_phentsize: read_u16(bytes, 54)?,
+            e_phnum: read_u16(bytes, 56)?,
+        })
+    }
+}
+
+struct Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+}
+
+impl Phdr {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < PHDR_SIZE {
+            return Err(Error::Uefi("ELF program header truncated".into()));
+        }
+
+        Ok(Phdr {
+            p_type: read_u32(bytes, 0)?,
+            p_flags: read_u32(bytes, 4)?,
+            p_offset: read_u64(bytes, 8)?,
+            p_vaddr: read_u64(bytes, 16)?,
+            p_filesz: read_u64(bytes, 32)?,
+            p_memsz: read_u64(bytes, 40)?,
+        })
+    }
+}
+
+pub struct LoadedSegment {
+    pub vaddr: u64,
+    pub frames: Vec<HostPhysFrame>,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+pub struct LoadedElf {
+    pub entry: u64,
+    pub segments: Vec<LoadedSegment>,
+}
+
+pub fn load(bytes: &[u8], alloc: &mut impl FrameAllocatorExt) -> Result<LoadedElf> {
+    let ehdr = Ehdr::parse(bytes)?;
+
+    let mut segments = Vec::new();
+    for i in 0..ehdr.e_phnum as usize {
+        let off = ehdr.e_phoff as usize + i * ehdr.e_phentsize as usize;
+        let phdr = Phdr::parse(
+            bytes
+                .get(off..)
+                .ok_or_else(|| Error::Uefi("ELF program header table truncated".into()))?,
+        )?;
+
+        if phdr.p_type != PT_LOAD {
+            continue;
+        }
+
+        segments.push(load_segment(bytes, &phdr, alloc)?);
+    }
+
+    Ok(LoadedElf {
+        entry: ehdr.e_entry,
+        segments,
+    })
+}
+
+fn load_segment(
+    bytes: &[u8],
+    phdr: &Phdr,
+    alloc: &mut impl FrameAllocatorExt,
+) -> Result<LoadedSegment> {
+    if phdr.p_filesz > phdr.p_memsz {
+        return Err(Error::Uefi("ELF segment has p_filesz > p_memsz".into()));
+    }
+
+    let file_start = phdr.p_offset as usize;
+    let file_end = file_start + phdr.p_filesz as usize;
+    let file_bytes = bytes
+        .get(file_start..file_end)
+        .ok_or_else(|| Error::Uefi("ELF segment extends past end of file".into()))?;
+
+    // p_vaddr isn't guaranteed to be page-aligned; size from the page.
+    let page_offset = phdr.p_vaddr & (PAGE_SIZE - 1);
+    let total_len = page_offset + phdr.p_memsz;
+    let page_count = (total_len + PAGE_SIZE - 1) / PAGE_SIZE;
+
+    // Allocate the whole segment as one contiguous run and skip the
+    // allocator's zeroing, since every byte in it is about to be written
+    // here anyway (either copied from the file or explicitly zeroed).
+    let base = alloc.allocate_frames(page_count, 1, false)?.start_address().as_u64();
+    let mut frames = Vec::with_capacity(page_count as usize);
+
+    for i in 0..page_count {
+        let page_start = i * PAGE_SIZE;
+        let dst = (base + page_start) as *mut u8;
+        let page_end = page_start + PAGE_SIZE;
+
+        // File bytes landing in this page, as offsets from the segment's
+        // first page (an empty range for pages past the file data).
+        let copy_start = page_start.max(page_offset);
+        let copy_end = page_end
+            .min(page_offset + file_bytes.len() as u64)
+            .max(copy_start);
+
+        unsafe {
+            if copy_end > copy_start {
+                let dst_off = (copy_start - page_start) as usize;
+                let src_off = (copy_start - page_offset) as usize;
+                let len = (copy_end - copy_start) as usize;
+                core::ptr::copy_nonoverlapping(file_bytes.as_ptr().add(src_off), dst.add(dst_off), len);
+            }
+            if copy_start > page_start {
+                core::ptr::write_bytes(dst, 0, (copy_start - page_start) as usize);
+            }
+            if copy_end < page_end {
+                let off = (copy_end - page_start) as usize;
+                core::ptr::write_bytes(dst.add(off), 0, (page_end - copy_end) as usize);
+            }
+        }
+
+        frames.push(HostPhysFrame::from_start_address(HostPhysAddr::new(
+            base + page_start,
+        ))?);
+    }
+
+    Ok(LoadedSegment {
+        vaddr: phdr.p_vaddr - page_offset,
+        frames,
+        writable: phdr.p_flags & PF_W != 0,
+        executable: phdr.p_flags & PF_X != 0,
+    })
+}